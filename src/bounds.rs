@@ -0,0 +1,148 @@
+use crate::tools::Tool;
+
+/// Geographic and pixel bounds of a rendered map, resolved from the
+/// center/zoom settings. An unset zoom is 0 and an unset center is lon/lat
+/// (0, 0); the extent of the added tools is not taken into account.
+pub struct Bounds {
+    /// Final output pixel width, i.e. the logical width times [scale_factor][Bounds::scale_factor].
+    pub width: u32,
+    /// Final output pixel height, i.e. the logical height times [scale_factor][Bounds::scale_factor].
+    pub height: u32,
+    pub zoom: u8,
+    pub tile_size: u32,
+    /// Device-pixel scale factor, e.g. 2.0 for an @2x/retina render.
+    pub scale_factor: f64,
+    pub x_min: i32,
+    pub x_max: i32,
+    pub y_min: i32,
+    pub y_max: i32,
+    x_center: f64,
+    y_center: f64,
+}
+
+impl Bounds {
+    /// Converts a tile-space x coordinate (in tile units) to a pixel offset
+    /// within the output image, accounting for [scale_factor][Bounds::scale_factor].
+    pub fn x_to_px(&self, x: f64) -> f64 {
+        (x - self.x_center) * self.tile_size as f64 * self.scale_factor + self.width as f64 / 2.0
+    }
+
+    /// Converts a tile-space y coordinate (in tile units) to a pixel offset
+    /// within the output image, accounting for [scale_factor][Bounds::scale_factor].
+    pub fn y_to_px(&self, y: f64) -> f64 {
+        (y - self.y_center) * self.tile_size as f64 * self.scale_factor + self.height as f64 / 2.0
+    }
+}
+
+/// Builder for [Bounds][Bounds].
+pub struct BoundsBuilder {
+    zoom: Option<u8>,
+    tile_size: u32,
+    scale_factor: f64,
+    lon_center: Option<f64>,
+    lat_center: Option<f64>,
+    padding: (u32, u32),
+    width: u32,
+    height: u32,
+}
+
+impl BoundsBuilder {
+    pub fn new() -> Self {
+        Self {
+            zoom: None,
+            tile_size: 256,
+            scale_factor: 1.0,
+            lon_center: None,
+            lat_center: None,
+            padding: (0, 0),
+            width: 300,
+            height: 300,
+        }
+    }
+
+    pub fn zoom(mut self, zoom: Option<u8>) -> Self {
+        self.zoom = zoom;
+        self
+    }
+
+    pub fn tile_size(mut self, tile_size: u32) -> Self {
+        self.tile_size = tile_size;
+        self
+    }
+
+    /// Device-pixel scale factor, e.g. 2.0 for an @2x/retina render.
+    /// Default is 1.0.
+    pub fn scale_factor(mut self, scale_factor: f64) -> Self {
+        self.scale_factor = scale_factor;
+        self
+    }
+
+    pub fn lon_center(mut self, lon_center: Option<f64>) -> Self {
+        self.lon_center = lon_center;
+        self
+    }
+
+    pub fn lat_center(mut self, lat_center: Option<f64>) -> Self {
+        self.lat_center = lat_center;
+        self
+    }
+
+    pub fn padding(mut self, padding: (u32, u32)) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn width(mut self, width: u32) -> Self {
+        self.width = width;
+        self
+    }
+
+    pub fn height(mut self, height: u32) -> Self {
+        self.height = height;
+        self
+    }
+
+    fn lon_to_x(lon: f64, zoom: u8) -> f64 {
+        (lon + 180.0) / 360.0 * 2_f64.powi(zoom.into())
+    }
+
+    fn lat_to_y(lat: f64, zoom: u8) -> f64 {
+        let lat_rad = lat.to_radians();
+        (1.0 - (lat_rad.tan() + 1.0 / lat_rad.cos()).ln() / std::f64::consts::PI) / 2.0
+            * 2_f64.powi(zoom.into())
+    }
+
+    /// Resolves the final [Bounds][Bounds] from the builder's settings. An
+    /// unset zoom falls back to 0 and an unset center to lon/lat (0, 0); the
+    /// tools are not read, so zoom and center are not fitted to their extent.
+    pub fn build(&self, _tools: &[Box<dyn Tool>]) -> Bounds {
+        let zoom = self.zoom.unwrap_or(0);
+        let lon_center = self.lon_center.unwrap_or(0.0);
+        let lat_center = self.lat_center.unwrap_or(0.0);
+
+        let x_center = Self::lon_to_x(lon_center, zoom);
+        let y_center = Self::lat_to_y(lat_center, zoom);
+
+        let tiles_x = (self.width + 2 * self.padding.0) as f64 / self.tile_size as f64;
+        let tiles_y = (self.height + 2 * self.padding.1) as f64 / self.tile_size as f64;
+
+        let x_min = (x_center - tiles_x / 2.0).floor() as i32;
+        let x_max = (x_center + tiles_x / 2.0).ceil() as i32;
+        let y_min = (y_center - tiles_y / 2.0).floor() as i32;
+        let y_max = (y_center + tiles_y / 2.0).ceil() as i32;
+
+        Bounds {
+            width: (self.width as f64 * self.scale_factor).round() as u32,
+            height: (self.height as f64 * self.scale_factor).round() as u32,
+            zoom,
+            tile_size: self.tile_size,
+            scale_factor: self.scale_factor,
+            x_min,
+            x_max,
+            y_min,
+            y_max,
+            x_center,
+            y_center,
+        }
+    }
+}
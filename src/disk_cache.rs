@@ -0,0 +1,142 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tiny_skia::Pixmap;
+
+use crate::{Error, Result};
+
+/// A disk-backed tile cache, storing each tile as `{base_dir}/{z}/{x}/{y}.png`
+/// alongside a `.meta` sidecar recording the fetch time. Tiles older than
+/// `ttl` are treated as a miss and re-fetched.
+///
+/// Used by [StaticMap][crate::StaticMap] as a layer between the in-memory
+/// cache and the network, so repeated renders of the same region don't
+/// re-download tiles between process runs.
+pub struct DiskCache {
+    base_dir: PathBuf,
+    ttl: Option<Duration>,
+}
+
+impl DiskCache {
+    /// `ttl` of `None` means cached tiles never expire.
+    pub fn new<P: AsRef<Path>>(base_dir: P, ttl: Option<Duration>) -> Self {
+        Self {
+            base_dir: base_dir.as_ref().to_path_buf(),
+            ttl,
+        }
+    }
+
+    fn tile_path(&self, z: u8, x: i32, y: i32) -> PathBuf {
+        self.base_dir
+            .join(z.to_string())
+            .join(x.to_string())
+            .join(format!("{}.png", y))
+    }
+
+    fn meta_path(&self, z: u8, x: i32, y: i32) -> PathBuf {
+        self.base_dir
+            .join(z.to_string())
+            .join(x.to_string())
+            .join(format!("{}.meta", y))
+    }
+
+    /// Returns the cached tile if present and not older than `ttl`.
+    pub fn get(&self, z: u8, x: i32, y: i32) -> Option<Pixmap> {
+        let fetched_at = std::fs::read_to_string(self.meta_path(z, x, y))
+            .ok()?
+            .trim()
+            .parse::<u64>()
+            .ok()?;
+
+        if let Some(ttl) = self.ttl {
+            let age = UNIX_EPOCH
+                .checked_add(Duration::from_secs(fetched_at))
+                .and_then(|fetched_at| SystemTime::now().duration_since(fetched_at).ok())?;
+            if age > ttl {
+                return None;
+            }
+        }
+
+        let bytes = std::fs::read(self.tile_path(z, x, y)).ok()?;
+        Pixmap::decode_png(&bytes).ok()
+    }
+
+    /// Writes `pixmap` and its fetch-time sidecar to disk.
+    pub fn put(&self, z: u8, x: i32, y: i32, pixmap: &Pixmap) -> Result<()> {
+        let tile_path = self.tile_path(z, x, y);
+        if let Some(parent) = tile_path.parent() {
+            std::fs::create_dir_all(parent).map_err(|error| Error::TileIoError {
+                error,
+                path: parent.to_path_buf(),
+            })?;
+        }
+
+        std::fs::write(&tile_path, pixmap.encode_png()?).map_err(|error| Error::TileIoError {
+            error,
+            path: tile_path.clone(),
+        })?;
+
+        let fetched_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let meta_path = self.meta_path(z, x, y);
+        std::fs::write(&meta_path, fetched_at.to_string()).map_err(|error| {
+            Error::TileIoError {
+                error,
+                path: meta_path.clone(),
+            }
+        })?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_cache(name: &str, ttl: Option<Duration>) -> DiskCache {
+        let dir = std::env::temp_dir().join(format!(
+            "staticmap-disk-cache-test-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        DiskCache::new(dir, ttl)
+    }
+
+    #[test]
+    fn expired_entry_is_treated_as_a_miss() {
+        let cache = temp_cache("ttl", Some(Duration::from_secs(60)));
+        let pixmap = Pixmap::new(1, 1).unwrap();
+        cache.put(1, 2, 3, &pixmap).unwrap();
+
+        assert!(cache.get(1, 2, 3).is_some());
+
+        let stale = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            - 3600;
+        std::fs::write(cache.meta_path(1, 2, 3), stale.to_string()).unwrap();
+
+        assert!(cache.get(1, 2, 3).is_none());
+
+        std::fs::remove_dir_all(&cache.base_dir).ok();
+    }
+
+    #[test]
+    fn ancient_entry_with_no_ttl_never_expires() {
+        let cache = temp_cache("no-ttl", None);
+        let pixmap = Pixmap::new(1, 1).unwrap();
+        cache.put(4, 5, 6, &pixmap).unwrap();
+
+        let ancient = 0u64;
+        std::fs::write(cache.meta_path(4, 5, 6), ancient.to_string()).unwrap();
+
+        assert!(cache.get(4, 5, 6).is_some());
+
+        std::fs::remove_dir_all(&cache.base_dir).ok();
+    }
+}
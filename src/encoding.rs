@@ -0,0 +1,74 @@
+use std::io::Cursor;
+use std::path::Path;
+
+use image::{ImageFormat as ImageCrateFormat, RgbaImage};
+use tiny_skia::Pixmap;
+
+use crate::{Error, Result};
+
+/// Output image format for [StaticMap::encode][crate::StaticMap::encode].
+pub enum ImageFormat {
+    Png,
+    /// `quality` ranges 1-100.
+    Jpeg { quality: u8 },
+    WebP,
+}
+
+impl ImageFormat {
+    /// Infers a format from a file extension, e.g. "jpg", "jpeg", "webp", "png".
+    /// Defaults to PNG for an unrecognized or missing extension.
+    pub fn from_extension<P: AsRef<Path>>(path: P) -> Self {
+        match path
+            .as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("jpg") | Some("jpeg") => ImageFormat::Jpeg { quality: 85 },
+            Some("webp") => ImageFormat::WebP,
+            _ => ImageFormat::Png,
+        }
+    }
+}
+
+/// Converts a [Pixmap][Pixmap]'s premultiplied-alpha pixels into the
+/// straight-alpha RGBA bytes the `image` crate expects.
+pub(crate) fn demultiply(pixmap: &Pixmap) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(pixmap.data().len());
+    for pixel in pixmap.pixels() {
+        let color = pixel.demultiply();
+        bytes.extend_from_slice(&[color.red(), color.green(), color.blue(), color.alpha()]);
+    }
+    bytes
+}
+
+/// Re-encodes a rendered [Pixmap][Pixmap] into `format` via the `image` crate.
+pub(crate) fn encode(pixmap: &Pixmap, format: ImageFormat) -> Result<Vec<u8>> {
+    if let ImageFormat::Png = format {
+        return Ok(pixmap.encode_png()?);
+    }
+
+    let image = RgbaImage::from_raw(pixmap.width(), pixmap.height(), demultiply(pixmap))
+        .ok_or(Error::InvalidSize)?;
+
+    let mut bytes = Vec::new();
+    let mut cursor = Cursor::new(&mut bytes);
+    match format {
+        ImageFormat::Png => unreachable!(),
+        ImageFormat::Jpeg { quality } => {
+            let encoder =
+                image::codecs::jpeg::JpegEncoder::new_with_quality(&mut cursor, quality);
+            image::DynamicImage::ImageRgba8(image)
+                .write_with_encoder(encoder)
+                .map_err(Error::ImageEncodingError)?;
+        }
+        ImageFormat::WebP => {
+            image
+                .write_to(&mut cursor, ImageCrateFormat::WebP)
+                .map_err(Error::ImageEncodingError)?;
+        }
+    }
+
+    Ok(bytes)
+}
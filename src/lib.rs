@@ -0,0 +1,58 @@
+//! Render static raster maps with markers, lines and other overlays baked in,
+//! without needing a browser or a tile-serving frontend.
+//!
+//! ## Example
+//! ```rust
+//! use staticmap::StaticMapBuilder;
+//!
+//! let mut map = StaticMapBuilder::new()
+//!     .width(300)
+//!     .height(300)
+//!     .zoom(4)
+//!     .lat_center(52.6)
+//!     .lon_center(13.4)
+//!     .build()
+//!     .unwrap();
+//! ```
+
+mod bounds;
+pub mod disk_cache;
+pub mod encoding;
+mod map;
+mod overzoom;
+pub mod tile_source;
+pub mod tools;
+
+pub use disk_cache::DiskCache;
+pub use encoding::ImageFormat;
+pub use map::{StaticMap, StaticMapBuilder};
+pub use tile_source::{HttpTileSource, LocalDirectoryTileSource, PmtilesTileSource, TileSource};
+
+use thiserror::Error as ThisError;
+
+/// Crate-wide result alias.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Errors produced while building or rendering a [StaticMap][StaticMap].
+#[derive(ThisError, Debug)]
+pub enum Error {
+    #[error("invalid image size")]
+    InvalidSize,
+    #[error("failed to fetch tile {url}: {error}")]
+    TileError { error: attohttpc::Error, url: String },
+    #[error("failed to decode tile as png: {0}")]
+    PngDecodingError(png::DecodingError),
+    #[error("failed to encode image: {0}")]
+    PngEncodingError(#[from] png::EncodingError),
+    #[error("I/O error on {path}: {error}")]
+    TileIoError {
+        error: std::io::Error,
+        path: std::path::PathBuf,
+    },
+    #[error("invalid pmtiles archive: {0}")]
+    PmtilesError(String),
+    #[error("no tile available for {z}/{x}/{y}")]
+    OverzoomUnavailable { z: u8, x: i32, y: i32 },
+    #[error("failed to encode image: {0}")]
+    ImageEncodingError(image::ImageError),
+}
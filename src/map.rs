@@ -1,14 +1,18 @@
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::{
     bounds::{Bounds, BoundsBuilder},
+    disk_cache::DiskCache,
+    encoding::{self, ImageFormat},
+    overzoom::{self, OverzoomSettings},
+    tile_source::{HttpTileSource, TileSource},
     tools::Tool,
     Error, Result,
 };
-use attohttpc::{Method, RequestBuilder, Response};
 use dashmap::DashMap;
 use rayon::{prelude::*, ThreadPoolBuilder};
-use retry::delay::Fixed;
 use tiny_skia::{Pixmap, PixmapMut, PixmapPaint, Transform};
 
 /// Main type.
@@ -29,7 +33,10 @@ use tiny_skia::{Pixmap, PixmapMut, PixmapPaint, Transform};
 ///
 /// ```
 pub struct StaticMap {
-    url_template: String,
+    tile_source: Box<dyn TileSource>,
+    disk_cache: Option<DiskCache>,
+    overzoom: OverzoomSettings,
+    concurrency: usize,
     tools: Vec<Box<dyn Tool>>,
     bounds: BoundsBuilder,
     tile_cache: Arc<DashMap<String, Pixmap>>,
@@ -43,8 +50,12 @@ pub struct StaticMapBuilder {
     zoom: Option<u8>,
     lat_center: Option<f64>,
     lon_center: Option<f64>,
-    url_template: String,
+    tile_source: Box<dyn TileSource>,
+    disk_cache: Option<DiskCache>,
+    overzoom: OverzoomSettings,
+    concurrency: usize,
     tile_size: u32,
+    scale_factor: f64,
     tile_cache: Arc<DashMap<String, Pixmap>>,
 }
 
@@ -57,8 +68,15 @@ impl Default for StaticMapBuilder {
             zoom: None,
             lat_center: None,
             lon_center: None,
-            url_template: "https://a.tile.osm.org/{z}/{x}/{y}.png".to_string(),
+            tile_source: Box::new(HttpTileSource::new(
+                "https://a.tile.osm.org/{z}/{x}/{y}.png",
+                "staticmap-rs",
+            )),
+            disk_cache: None,
+            overzoom: OverzoomSettings::default(),
+            concurrency: 24,
             tile_size: 256,
+            scale_factor: 1.0,
             tile_cache: Arc::new(DashMap::new()),
         }
     }
@@ -114,8 +132,20 @@ impl StaticMapBuilder {
 
     /// URL template, e.g. "https://example.com/{z}/{x}/{y}.png".
     /// Default is "https://a.tile.osm.org/{z}/{x}/{y}.png".
+    ///
+    /// Shorthand for `.tile_source(HttpTileSource::new(url_template, "staticmap-rs"))`;
+    /// superseded by a later call to [tile_source][StaticMapBuilder::tile_source].
     pub fn url_template<I: Into<String>>(mut self, url_template: I) -> Self {
-        self.url_template = url_template.into();
+        self.tile_source = Box::new(HttpTileSource::new(url_template, "staticmap-rs"));
+        self
+    }
+
+    /// Sets the [TileSource][TileSource] tiles are fetched from, e.g. an
+    /// [HttpTileSource][HttpTileSource], [LocalDirectoryTileSource][crate::tile_source::LocalDirectoryTileSource],
+    /// or [PmtilesTileSource][crate::tile_source::PmtilesTileSource]. Supersedes any
+    /// previous call to [url_template][StaticMapBuilder::url_template] or `tile_source`.
+    pub fn tile_source(mut self, tile_source: impl TileSource + 'static) -> Self {
+        self.tile_source = Box::new(tile_source);
         self
     }
 
@@ -125,6 +155,38 @@ impl StaticMapBuilder {
         self
     }
 
+    /// Caches fetched tiles on disk under `path`, persisting them between
+    /// process runs. A tile older than `ttl` is considered stale and
+    /// re-fetched from the [TileSource][TileSource].
+    pub fn disk_cache<P: AsRef<Path>>(mut self, path: P, ttl: Duration) -> Self {
+        self.disk_cache = Some(DiskCache::new(path, Some(ttl)));
+        self
+    }
+
+    /// Caps the zoom level actually requested from the [TileSource][TileSource]; any
+    /// requested zoom beyond it is synthesized by upscaling tiles from this zoom
+    /// instead of querying the source for a zoom it doesn't have. This takes
+    /// effect only with [overzoom_fallback][StaticMapBuilder::overzoom_fallback]
+    /// enabled; otherwise a tile beyond it fails the render.
+    pub fn max_source_zoom(mut self, max_source_zoom: u8) -> Self {
+        self.overzoom.max_source_zoom = Some(max_source_zoom);
+        self
+    }
+
+    /// When enabled, a tile the source can't provide is synthesized from a
+    /// neighboring zoom level instead of failing the whole render. Off by default.
+    pub fn overzoom_fallback(mut self, enabled: bool) -> Self {
+        self.overzoom.enabled = enabled;
+        self
+    }
+
+    /// Size of the thread pool used to fetch tiles concurrently.
+    /// Default is 24.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
     /// Tile size, in pixels.
     /// Default is 256.
     pub fn tile_size(mut self, tile_size: u32) -> Self {
@@ -132,11 +194,20 @@ impl StaticMapBuilder {
         self
     }
 
+    /// Device-pixel scale factor for high-DPI output, e.g. 2.0 for an
+    /// @2x/retina render. Doubles the output image dimensions and upscales
+    /// fetched tiles to match. Default is 1.0.
+    pub fn scale_factor(mut self, scale_factor: f64) -> Self {
+        self.scale_factor = scale_factor;
+        self
+    }
+
     /// Consumes the builder.
     pub fn build(self) -> Result<StaticMap> {
         let bounds = BoundsBuilder::new()
             .zoom(self.zoom)
             .tile_size(self.tile_size)
+            .scale_factor(self.scale_factor)
             .lon_center(self.lon_center)
             .lat_center(self.lat_center)
             .padding(self.padding)
@@ -144,7 +215,10 @@ impl StaticMapBuilder {
             .width(self.width);
 
         Ok(StaticMap {
-            url_template: self.url_template,
+            tile_source: self.tile_source,
+            disk_cache: self.disk_cache,
+            overzoom: self.overzoom,
+            concurrency: self.concurrency,
             tools: Vec::new(),
             bounds,
             tile_cache: self.tile_cache,
@@ -173,6 +247,35 @@ impl StaticMap {
         Ok(())
     }
 
+    /// Render the map and encode it as `format` (PNG, JPEG or WebP).
+    ///
+    /// May panic if any feature has invalid bounds.
+    pub fn encode(&mut self, format: ImageFormat) -> Result<Vec<u8>> {
+        encoding::encode(&self.render()?, format)
+    }
+
+    /// Render the map and save it to `path`, inferring the output format
+    /// from the file extension (falling back to PNG).
+    ///
+    /// May panic if any feature has invalid bounds.
+    pub fn save<P: AsRef<::std::path::Path>>(&mut self, path: P) -> Result<()> {
+        let format = ImageFormat::from_extension(&path);
+        let bytes = self.encode(format)?;
+        std::fs::write(&path, bytes).map_err(|error| Error::TileIoError {
+            error,
+            path: path.as_ref().to_path_buf(),
+        })
+    }
+
+    /// Render the map and return its raw `(width, height, rgba_bytes)`
+    /// pixel buffer, for callers that want to feed the output into their
+    /// own image pipeline.
+    pub fn rgba(&mut self) -> Result<(u32, u32, Vec<u8>)> {
+        let image = self.render()?;
+        let bytes = encoding::demultiply(&image);
+        Ok((image.width(), image.height(), bytes))
+    }
+
     fn render(&mut self) -> Result<Pixmap> {
         let bounds = self.bounds.build(&self.tools);
 
@@ -190,7 +293,7 @@ impl StaticMap {
     fn draw_base_layer(&self, mut image: PixmapMut, bounds: &Bounds) -> Result<()> {
         let max_tile: i32 = 2_i32.pow(bounds.zoom.into());
 
-        let tiles: Vec<(i32, i32, String)> = (bounds.x_min..bounds.x_max)
+        let tiles: Vec<(i32, i32, i32, i32, String)> = (bounds.x_min..bounds.x_max)
             .map(|x| (x, bounds.y_min..bounds.y_max))
             .flat_map(|(x, y_r)| {
                 y_r.map(move |y| {
@@ -200,43 +303,52 @@ impl StaticMap {
                     (
                         x,
                         y,
-                        self.url_template
-                            .replace("{z}", &bounds.zoom.to_string())
-                            .replace("{x}", &tile_x.to_string())
-                            .replace("{y}", &tile_y.to_string()),
+                        tile_x,
+                        tile_y,
+                        format!("{}/{}/{}", bounds.zoom, tile_x, tile_y),
                     )
                 })
             })
             .collect();
         let cache = &self.tile_cache;
+        let tile_source = &self.tile_source;
+        let disk_cache = &self.disk_cache;
 
-        let thread_pool = ThreadPoolBuilder::new().num_threads(24).build().unwrap();
+        let thread_pool = ThreadPoolBuilder::new()
+            .num_threads(self.concurrency)
+            .build()
+            .unwrap();
         let tile_images: Vec<std::result::Result<Pixmap, Error>> = thread_pool.install(|| {
             tiles
                 .par_iter()
-                .map(|x| {
-                    if let Some(cached) = cache.get(&x.2) {
-                        Ok(cached.clone())
-                    } else {
-                        retry::retry(Fixed::from_millis(1000).take(5), || {
-                            RequestBuilder::try_new(Method::GET, &x.2)
-                                .and_then(RequestBuilder::send)
-                                .and_then(Response::bytes)
-                                .map_err(|error| Error::TileError {
-                                    error,
-                                    url: x.2.clone(),
-                                })
-                                .and_then(|bytes| {
-                                    Pixmap::decode_png(&bytes)
-                                        .map_err(|e| Error::PngDecodingError(e))
-                                })
-                                .map(|r| {
-                                    cache.insert(x.2.clone(), r.clone());
-                                    r
-                                })
-                        })
-                        .map_err(|e| e.error)
+                .map(|tile| {
+                    if let Some(cached) = cache.get(&tile.4) {
+                        return Ok(cached.clone());
                     }
+
+                    if let Some(cached) = disk_cache
+                        .as_ref()
+                        .and_then(|disk_cache| disk_cache.get(bounds.zoom, tile.2, tile.3))
+                    {
+                        cache.insert(tile.4.clone(), cached.clone());
+                        return Ok(cached);
+                    }
+
+                    overzoom::fetch_tile(
+                        tile_source.as_ref(),
+                        bounds.zoom,
+                        tile.2,
+                        tile.3,
+                        bounds.tile_size,
+                        &self.overzoom,
+                    )
+                    .map(|r| {
+                        if let Some(disk_cache) = disk_cache {
+                            let _ = disk_cache.put(bounds.zoom, tile.2, tile.3, &r);
+                        }
+                        cache.insert(tile.4.clone(), r.clone());
+                        r
+                    })
                 })
                 .collect()
         });
@@ -246,11 +358,12 @@ impl StaticMap {
             let (x_px, y_px) = (bounds.x_to_px(x.into()), bounds.y_to_px(y.into()));
 
             image.draw_pixmap(
-                x_px as i32,
-                y_px as i32,
+                0,
+                0,
                 pixmap?.as_ref(),
                 &PixmapPaint::default(),
-                Transform::default(),
+                Transform::from_scale(bounds.scale_factor as f32, bounds.scale_factor as f32)
+                    .post_translate(x_px as f32, y_px as f32),
                 None,
             );
         }
@@ -258,3 +371,42 @@ impl StaticMap {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiny_skia::Color;
+
+    struct SolidTileSource;
+
+    impl TileSource for SolidTileSource {
+        fn fetch(&self, _z: u8, _x: i32, _y: i32) -> Result<Pixmap> {
+            let mut pixmap = Pixmap::new(4, 4).ok_or(Error::InvalidSize)?;
+            pixmap.fill(Color::from_rgba8(255, 0, 0, 255));
+            Ok(pixmap)
+        }
+    }
+
+    #[test]
+    fn scale_factor_places_tiles_without_doubling_their_offset() {
+        let mut map = StaticMapBuilder::new()
+            .width(8)
+            .height(8)
+            .tile_size(4)
+            .scale_factor(2.0)
+            .zoom(1)
+            .lat_center(0.0)
+            .lon_center(0.0)
+            .tile_source(SolidTileSource)
+            .build()
+            .unwrap();
+
+        let image = map.render().unwrap();
+        assert_eq!((image.width(), image.height()), (16, 16));
+
+        // The right-hand tile is placed at x_px == 8 (pre-scale tile offset),
+        // not 2 * 8 == 16, which would push it entirely off the canvas.
+        let pixel = image.pixel(12, 4).unwrap();
+        assert_eq!((pixel.red(), pixel.green(), pixel.blue()), (255, 0, 0));
+    }
+}
@@ -0,0 +1,251 @@
+use tiny_skia::{Pixmap, PixmapPaint, Transform};
+
+use crate::{tile_source::TileSource, Error, Result};
+
+/// Controls how [StaticMap][crate::StaticMap] reacts to a tile that the
+/// [TileSource][TileSource] can't directly provide, either because it 404s
+/// or because the requested zoom exceeds the source's available range.
+#[derive(Clone, Copy)]
+pub struct OverzoomSettings {
+    pub enabled: bool,
+    pub max_source_zoom: Option<u8>,
+}
+
+impl Default for OverzoomSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_source_zoom: None,
+        }
+    }
+}
+
+/// Highest zoom `downscale_from_children` will recurse up to before giving
+/// up; bounds the search when a tile is missing at every zoom level instead
+/// of recursing until `z + 1` overflows `u8`.
+const MAX_DOWNSCALE_ZOOM: u8 = 22;
+
+/// Fetches the tile at `z`/`x`/`y`, synthesizing it from a neighboring zoom
+/// level when it isn't directly available and `settings.enabled` is set.
+pub(crate) fn fetch_tile(
+    source: &dyn TileSource,
+    z: u8,
+    x: i32,
+    y: i32,
+    tile_size: u32,
+    settings: &OverzoomSettings,
+) -> Result<Pixmap> {
+    let over_budget = settings
+        .max_source_zoom
+        .map(|max| z > max)
+        .unwrap_or(false);
+
+    if !over_budget {
+        match source.fetch(z, x, y) {
+            Ok(pixmap) => return Ok(pixmap),
+            Err(error) => {
+                if !settings.enabled {
+                    return Err(error);
+                }
+            }
+        }
+    } else if !settings.enabled {
+        return Err(Error::OverzoomUnavailable { z, x, y });
+    }
+
+    if over_budget {
+        return upscale_from_parent(source, z, x, y, tile_size, settings);
+    }
+
+    upscale_from_parent(source, z, x, y, tile_size, settings)
+        .or_else(|_| downscale_from_children(source, z, x, y, tile_size, settings))
+}
+
+/// Synthesizes the missing tile by fetching its parent at `z - 1` and
+/// cropping the quadrant that corresponds to `(x, y)`, then scaling it back
+/// up to `tile_size`.
+///
+/// Recurses only downward through `upscale_from_parent` itself rather than
+/// back through [fetch_tile][fetch_tile] — otherwise a parent that's also
+/// missing would bounce into `downscale_from_children` and back, oscillating
+/// between the two instead of bottoming out at `z == 0`.
+fn upscale_from_parent(
+    source: &dyn TileSource,
+    z: u8,
+    x: i32,
+    y: i32,
+    tile_size: u32,
+    settings: &OverzoomSettings,
+) -> Result<Pixmap> {
+    if z == 0 {
+        return Err(Error::OverzoomUnavailable { z, x, y });
+    }
+
+    let (parent_z, parent_x, parent_y) = (z - 1, x >> 1, y >> 1);
+    let parent_over_budget = settings.max_source_zoom.map_or(false, |max| parent_z > max);
+    let fetched = if parent_over_budget {
+        None
+    } else {
+        source.fetch(parent_z, parent_x, parent_y).ok()
+    };
+    let parent = match fetched {
+        Some(pixmap) => pixmap,
+        None => upscale_from_parent(source, parent_z, parent_x, parent_y, tile_size, settings)?,
+    };
+
+    let half = tile_size / 2;
+    let mut quadrant = Pixmap::new(half, half).ok_or(Error::InvalidSize)?;
+    quadrant.draw_pixmap(
+        -((x & 1) * half as i32),
+        -((y & 1) * half as i32),
+        parent.as_ref(),
+        &PixmapPaint::default(),
+        Transform::identity(),
+        None,
+    );
+
+    let mut scaled = Pixmap::new(tile_size, tile_size).ok_or(Error::InvalidSize)?;
+    scaled.draw_pixmap(
+        0,
+        0,
+        quadrant.as_ref(),
+        &PixmapPaint::default(),
+        Transform::from_scale(2.0, 2.0),
+        None,
+    );
+
+    Ok(scaled)
+}
+
+/// Synthesizes the missing tile by fetching its four children at `z + 1`,
+/// compositing them into a `2 * tile_size` square, and downsampling.
+///
+/// Recurses only upward through `downscale_from_children` itself rather than
+/// back through [fetch_tile][fetch_tile], for the same reason
+/// [upscale_from_parent][upscale_from_parent] does: it bottoms out at
+/// `MAX_DOWNSCALE_ZOOM` instead of oscillating with the upscale path.
+fn downscale_from_children(
+    source: &dyn TileSource,
+    z: u8,
+    x: i32,
+    y: i32,
+    tile_size: u32,
+    settings: &OverzoomSettings,
+) -> Result<Pixmap> {
+    if z >= MAX_DOWNSCALE_ZOOM {
+        return Err(Error::OverzoomUnavailable { z, x, y });
+    }
+
+    let child_z = z + 1;
+    let children = [
+        (2 * x, 2 * y, 0, 0),
+        (2 * x + 1, 2 * y, tile_size, 0),
+        (2 * x, 2 * y + 1, 0, tile_size),
+        (2 * x + 1, 2 * y + 1, tile_size, tile_size),
+    ];
+
+    let mut composed = Pixmap::new(tile_size * 2, tile_size * 2).ok_or(Error::InvalidSize)?;
+    for (child_x, child_y, ox, oy) in children {
+        let child = match source.fetch(child_z, child_x, child_y) {
+            Ok(pixmap) => pixmap,
+            Err(_) => downscale_from_children(source, child_z, child_x, child_y, tile_size, settings)?,
+        };
+        composed.draw_pixmap(
+            ox as i32,
+            oy as i32,
+            child.as_ref(),
+            &PixmapPaint::default(),
+            Transform::identity(),
+            None,
+        );
+    }
+
+    let mut scaled = Pixmap::new(tile_size, tile_size).ok_or(Error::InvalidSize)?;
+    scaled.draw_pixmap(
+        0,
+        0,
+        composed.as_ref(),
+        &PixmapPaint::default(),
+        Transform::from_scale(0.5, 0.5),
+        None,
+    );
+
+    Ok(scaled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Always serves the same parent tile, with a red top-left quadrant and
+    /// the other three quadrants green, regardless of the z/x/y requested.
+    struct QuadrantSource(Pixmap);
+
+    impl TileSource for QuadrantSource {
+        fn fetch(&self, _z: u8, _x: i32, _y: i32) -> Result<Pixmap> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn quadrant_pixmap(size: u32) -> Pixmap {
+        let half = size / 2;
+        let mut pixmap = Pixmap::new(size, size).unwrap();
+        for y in 0..size {
+            for x in 0..size {
+                let color = if x < half && y < half {
+                    tiny_skia::PremultipliedColorU8::from_rgba(255, 0, 0, 255).unwrap()
+                } else {
+                    tiny_skia::PremultipliedColorU8::from_rgba(0, 255, 0, 255).unwrap()
+                };
+                pixmap.pixels_mut()[(y * size + x) as usize] = color;
+            }
+        }
+        pixmap
+    }
+
+    #[test]
+    fn upscale_from_parent_crops_the_matching_quadrant() {
+        let source = QuadrantSource(quadrant_pixmap(4));
+        let settings = OverzoomSettings {
+            enabled: true,
+            max_source_zoom: Some(0),
+        };
+
+        // z = 1 is over budget, forcing the (0, 0) child to be synthesized
+        // from the parent's top-left (red) quadrant.
+        let tile = fetch_tile(&source, 1, 0, 0, 4, &settings).unwrap();
+        let pixel = tile.pixel(0, 0).unwrap();
+        assert_eq!((pixel.red(), pixel.green(), pixel.blue()), (255, 0, 0));
+
+        // the (1, 1) child instead comes from the bottom-right (green) quadrant.
+        let tile = fetch_tile(&source, 1, 1, 1, 4, &settings).unwrap();
+        let pixel = tile.pixel(0, 0).unwrap();
+        assert_eq!((pixel.red(), pixel.green(), pixel.blue()), (0, 255, 0));
+    }
+
+    /// Always fails, regardless of z/x/y.
+    struct AlwaysMissingSource;
+
+    impl TileSource for AlwaysMissingSource {
+        fn fetch(&self, z: u8, x: i32, y: i32) -> Result<Pixmap> {
+            Err(Error::OverzoomUnavailable { z, x, y })
+        }
+    }
+
+    #[test]
+    fn fetch_tile_degrades_gracefully_when_every_zoom_is_missing() {
+        let settings = OverzoomSettings {
+            enabled: true,
+            max_source_zoom: None,
+        };
+
+        // z = 0 is the most realistic starting point and also the worst
+        // case: the upscale chain fails in a single step (z == 0), then the
+        // downscale chain walks all the way up to MAX_DOWNSCALE_ZOOM before
+        // giving up. Each of the two chains only ever follows one branch
+        // (the first failing child), so this terminates in O(MAX_DOWNSCALE_ZOOM)
+        // steps rather than recursing indefinitely.
+        let result = fetch_tile(&AlwaysMissingSource, 0, 0, 0, 4, &settings);
+        assert!(matches!(result, Err(Error::OverzoomUnavailable { .. })));
+    }
+}
@@ -0,0 +1,466 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use attohttpc::{Method, RequestBuilder};
+use flate2::read::GzDecoder;
+use retry::delay::Fixed;
+use tiny_skia::Pixmap;
+
+use crate::{Error, Result};
+
+/// Supplies tile images for [StaticMap][crate::StaticMap] to composite into
+/// the base layer. Implementations may fetch tiles over the network, read
+/// them from disk, or pull them out of an archive format.
+pub trait TileSource: Send + Sync {
+    /// Fetch the tile at `z`/`x`/`y` and decode it into a [Pixmap][Pixmap].
+    fn fetch(&self, z: u8, x: i32, y: i32) -> Result<Pixmap>;
+}
+
+/// Retry policy applied to a single tile fetch.
+#[derive(Clone, Copy)]
+pub struct RetryPolicy {
+    pub attempts: usize,
+    pub delay_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            delay_ms: 1000,
+        }
+    }
+}
+
+/// A simple limiter that paces fetches to at most `requests_per_second` by
+/// keeping consecutive requests at least `1 / requests_per_second` apart,
+/// shared across threads. It allows no bursts.
+struct RateLimiter {
+    interval: Duration,
+    last: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    fn new(requests_per_second: f64) -> Self {
+        Self {
+            interval: Duration::from_secs_f64(1.0 / requests_per_second),
+            last: Mutex::new(None),
+        }
+    }
+
+    fn acquire(&self) {
+        let mut last = self.last.lock().unwrap();
+        let now = Instant::now();
+        if let Some(earliest) = last.map(|last| last + self.interval) {
+            if earliest > now {
+                std::thread::sleep(earliest - now);
+            }
+        }
+        *last = Some(Instant::now());
+    }
+}
+
+/// Fetches tiles from a templated HTTP(S) tile server, e.g.
+/// `https://a.tile.osm.org/{z}/{x}/{y}.png`.
+///
+/// A `user_agent` is required: most tile providers (e.g. OpenStreetMap)
+/// require one and will block requests without it.
+pub struct HttpTileSource {
+    url_template: String,
+    user_agent: String,
+    headers: Vec<(String, String)>,
+    retry_policy: RetryPolicy,
+    rate_limiter: Option<RateLimiter>,
+}
+
+impl HttpTileSource {
+    pub fn new<T: Into<String>, A: Into<String>>(url_template: T, user_agent: A) -> Self {
+        Self {
+            url_template: url_template.into(),
+            user_agent: user_agent.into(),
+            headers: Vec::new(),
+            retry_policy: RetryPolicy::default(),
+            rate_limiter: None,
+        }
+    }
+
+    /// Adds a header sent with every tile request, e.g. for an API key.
+    pub fn header<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Paces outbound tile requests to at most `requests_per_second`.
+    pub fn requests_per_second(mut self, requests_per_second: f64) -> Self {
+        self.rate_limiter = Some(RateLimiter::new(requests_per_second));
+        self
+    }
+
+    /// Overrides the default retry policy (5 attempts, 1000ms apart).
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    fn url(&self, z: u8, x: i32, y: i32) -> String {
+        self.url_template
+            .replace("{z}", &z.to_string())
+            .replace("{x}", &x.to_string())
+            .replace("{y}", &y.to_string())
+    }
+}
+
+impl TileSource for HttpTileSource {
+    fn fetch(&self, z: u8, x: i32, y: i32) -> Result<Pixmap> {
+        let url = self.url(z, x, y);
+
+        retry::retry(
+            // retry::retry makes one try plus one per delay, so `attempts`
+            // tries in all need `attempts - 1` delays.
+            Fixed::from_millis(self.retry_policy.delay_ms)
+                .take(self.retry_policy.attempts.saturating_sub(1)),
+            || {
+                if let Some(rate_limiter) = &self.rate_limiter {
+                    rate_limiter.acquire();
+                }
+
+                RequestBuilder::try_new(Method::GET, &url)
+                    .map(|request| {
+                        let mut request = request.header("User-Agent", &self.user_agent);
+                        for (name, value) in &self.headers {
+                            request = request.header(name, value);
+                        }
+                        request
+                    })
+                    .and_then(attohttpc::RequestBuilder::send)
+                    .and_then(attohttpc::Response::bytes)
+                    .map_err(|error| Error::TileError {
+                        error,
+                        url: url.clone(),
+                    })
+                    .and_then(|bytes| Pixmap::decode_png(&bytes).map_err(Error::PngDecodingError))
+            },
+        )
+        .map_err(|e| e.error)
+    }
+}
+
+/// Reads pre-rendered tiles from a local directory laid out as
+/// `{base_dir}/{z}/{x}/{y}.png`, for rendering without any network access.
+pub struct LocalDirectoryTileSource {
+    base_dir: PathBuf,
+}
+
+impl LocalDirectoryTileSource {
+    pub fn new<P: AsRef<Path>>(base_dir: P) -> Self {
+        Self {
+            base_dir: base_dir.as_ref().to_path_buf(),
+        }
+    }
+
+    fn path(&self, z: u8, x: i32, y: i32) -> PathBuf {
+        self.base_dir
+            .join(z.to_string())
+            .join(x.to_string())
+            .join(format!("{}.png", y))
+    }
+}
+
+impl TileSource for LocalDirectoryTileSource {
+    fn fetch(&self, z: u8, x: i32, y: i32) -> Result<Pixmap> {
+        let path = self.path(z, x, y);
+        let bytes = std::fs::read(&path).map_err(|error| Error::TileIoError {
+            error,
+            path: path.clone(),
+        })?;
+        Pixmap::decode_png(&bytes).map_err(Error::PngDecodingError)
+    }
+}
+
+/// Reads tiles out of a single [PMTiles](https://github.com/protomaps/PMTiles)
+/// archive, looking up the byte range for a z/x/y key in the archive's
+/// directory and decompressing the tile data it points to.
+pub struct PmtilesTileSource {
+    file: std::sync::Mutex<File>,
+    root_directory: Vec<PmtilesEntry>,
+    leaf_directories_offset: u64,
+    tile_data_offset: u64,
+    internal_compression: PmtilesCompression,
+    tile_compression: PmtilesCompression,
+}
+
+#[derive(Clone, Copy)]
+struct PmtilesEntry {
+    tile_id: u64,
+    offset: u64,
+    length: u32,
+    run_length: u32,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PmtilesCompression {
+    None,
+    Gzip,
+}
+
+impl PmtilesCompression {
+    fn from_byte(b: u8) -> Self {
+        match b {
+            2 => PmtilesCompression::Gzip,
+            _ => PmtilesCompression::None,
+        }
+    }
+
+    fn decompress(self, bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            PmtilesCompression::None => Ok(bytes.to_vec()),
+            PmtilesCompression::Gzip => {
+                let mut out = Vec::new();
+                GzDecoder::new(bytes).read_to_end(&mut out)?;
+                Ok(out)
+            }
+        }
+    }
+}
+
+impl PmtilesTileSource {
+    /// Opens a `.pmtiles` archive, parsing its header and root directory.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut file = File::open(path).map_err(|error| Error::TileIoError {
+            error,
+            path: path.to_path_buf(),
+        })?;
+
+        let mut header = [0u8; 127];
+        file.read_exact(&mut header)
+            .map_err(|error| Error::TileIoError {
+                error,
+                path: path.to_path_buf(),
+            })?;
+        if &header[0..7] != b"PMTiles" {
+            return Err(Error::PmtilesError(format!(
+                "{} is not a pmtiles archive",
+                path.display()
+            )));
+        }
+
+        let root_dir_offset = u64::from_le_bytes(header[8..16].try_into().unwrap());
+        let root_dir_length = u64::from_le_bytes(header[16..24].try_into().unwrap());
+        let leaf_directories_offset = u64::from_le_bytes(header[40..48].try_into().unwrap());
+        let tile_data_offset = u64::from_le_bytes(header[56..64].try_into().unwrap());
+        let internal_compression = PmtilesCompression::from_byte(header[97]);
+        let tile_compression = PmtilesCompression::from_byte(header[98]);
+
+        let mut raw_dir = vec![0u8; root_dir_length as usize];
+        read_at(&mut file, root_dir_offset, &mut raw_dir).map_err(|error| Error::TileIoError {
+            error,
+            path: path.to_path_buf(),
+        })?;
+        let raw_dir = internal_compression
+            .decompress(&raw_dir)
+            .map_err(|error| Error::TileIoError {
+                error,
+                path: path.to_path_buf(),
+            })?;
+
+        let root_directory = parse_directory(&raw_dir);
+
+        Ok(Self {
+            file: std::sync::Mutex::new(file),
+            root_directory,
+            leaf_directories_offset,
+            tile_data_offset,
+            internal_compression,
+            tile_compression,
+        })
+    }
+
+    /// Resolves `tile_id` to its entry, descending through leaf directories
+    /// (entries with `run_length == 0`, per the PMTiles v3 spec) as needed.
+    fn resolve(&self, tile_id: u64) -> Result<Option<PmtilesEntry>> {
+        let mut directory = Cow::Borrowed(self.root_directory.as_slice());
+        loop {
+            match find_entry(&directory, tile_id) {
+                Some(entry) if entry.run_length == 0 => {
+                    directory = Cow::Owned(self.read_leaf_directory(entry.offset, entry.length)?);
+                }
+                found => return Ok(found),
+            }
+        }
+    }
+
+    fn read_leaf_directory(&self, offset: u64, length: u32) -> Result<Vec<PmtilesEntry>> {
+        let mut file = self.file.lock().unwrap();
+        let mut raw = vec![0u8; length as usize];
+        read_at(&mut file, self.leaf_directories_offset + offset, &mut raw).map_err(
+            |error| Error::TileIoError {
+                error,
+                path: PathBuf::new(),
+            },
+        )?;
+        drop(file);
+
+        let raw = self
+            .internal_compression
+            .decompress(&raw)
+            .map_err(|error| Error::TileIoError {
+                error,
+                path: PathBuf::new(),
+            })?;
+
+        Ok(parse_directory(&raw))
+    }
+}
+
+impl TileSource for PmtilesTileSource {
+    fn fetch(&self, z: u8, x: i32, y: i32) -> Result<Pixmap> {
+        if self.root_directory.is_empty() {
+            return Err(Error::PmtilesError(format!(
+                "tile {}/{}/{} not found",
+                z, x, y
+            )));
+        }
+
+        let tile_id = zxy_to_tile_id(z, x as u32, y as u32);
+        let entry = self
+            .resolve(tile_id)?
+            .ok_or_else(|| Error::PmtilesError(format!("tile {}/{}/{} not found", z, x, y)))?;
+
+        let mut file = self.file.lock().unwrap();
+        let mut raw = vec![0u8; entry.length as usize];
+        read_at(&mut file, self.tile_data_offset + entry.offset, &mut raw).map_err(|error| {
+            Error::TileIoError {
+                error,
+                path: PathBuf::new(),
+            }
+        })?;
+        drop(file);
+
+        let bytes = self
+            .tile_compression
+            .decompress(&raw)
+            .map_err(|error| Error::TileIoError {
+                error,
+                path: PathBuf::new(),
+            })?;
+
+        Pixmap::decode_png(&bytes).map_err(Error::PngDecodingError)
+    }
+}
+
+/// Finds the entry covering `tile_id` in a single (root or leaf) directory.
+fn find_entry(directory: &[PmtilesEntry], tile_id: u64) -> Option<PmtilesEntry> {
+    match directory.binary_search_by(|entry| entry.tile_id.cmp(&tile_id)) {
+        Ok(i) => Some(directory[i]),
+        Err(i) => {
+            let entry = directory.get(i.checked_sub(1)?)?;
+            // A run_length of 0 marks a leaf-directory pointer, which covers
+            // every tile_id up to the next root entry rather than a fixed run.
+            if entry.run_length == 0 || tile_id < entry.tile_id + entry.run_length as u64 {
+                Some(*entry)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+fn read_at(file: &mut File, offset: u64, buf: &mut [u8]) -> std::io::Result<()> {
+    use std::io::{Seek, SeekFrom};
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buf)
+}
+
+fn read_varint(buf: &[u8], pos: &mut usize) -> u64 {
+    let mut result = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = buf[*pos];
+        *pos += 1;
+        result |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    result
+}
+
+fn parse_directory(buf: &[u8]) -> Vec<PmtilesEntry> {
+    let mut pos = 0;
+    let num_entries = read_varint(buf, &mut pos) as usize;
+
+    let mut tile_ids = Vec::with_capacity(num_entries);
+    let mut last = 0u64;
+    for _ in 0..num_entries {
+        last += read_varint(buf, &mut pos);
+        tile_ids.push(last);
+    }
+
+    let mut run_lengths = Vec::with_capacity(num_entries);
+    for _ in 0..num_entries {
+        run_lengths.push(read_varint(buf, &mut pos) as u32);
+    }
+
+    let mut lengths = Vec::with_capacity(num_entries);
+    for _ in 0..num_entries {
+        lengths.push(read_varint(buf, &mut pos) as u32);
+    }
+
+    let mut offsets = Vec::with_capacity(num_entries);
+    let mut last_offset = 0u64;
+    for i in 0..num_entries {
+        let v = read_varint(buf, &mut pos);
+        let offset = if v == 0 {
+            last_offset + lengths[i.saturating_sub(1)] as u64
+        } else {
+            v - 1
+        };
+        offsets.push(offset);
+        last_offset = offset;
+    }
+
+    (0..num_entries)
+        .map(|i| PmtilesEntry {
+            tile_id: tile_ids[i],
+            offset: offsets[i],
+            length: lengths[i],
+            run_length: run_lengths[i],
+        })
+        .collect()
+}
+
+/// Converts z/x/y into the Hilbert-curve tile id used as the PMTiles
+/// directory key, per the PMTiles spec.
+fn zxy_to_tile_id(z: u8, x: u32, y: u32) -> u64 {
+    let mut acc = 0u64;
+    for t in 0..z {
+        acc += 4u64.pow(t as u32);
+    }
+
+    let n = 1u32 << z;
+    let (mut rx, mut ry, mut d) = (0u32, 0u32, 0u64);
+    let (mut x, mut y) = (x, y);
+    let mut s = n / 2;
+    while s > 0 {
+        rx = if (x & s) > 0 { 1 } else { 0 };
+        ry = if (y & s) > 0 { 1 } else { 0 };
+        d += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+
+        if ry == 0 {
+            if rx == 1 {
+                x = s.wrapping_sub(1).wrapping_sub(x) & (s.wrapping_mul(2).wrapping_sub(1));
+                y = s.wrapping_sub(1).wrapping_sub(y) & (s.wrapping_mul(2).wrapping_sub(1));
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+
+    acc + d
+}
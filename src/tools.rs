@@ -0,0 +1,11 @@
+use tiny_skia::PixmapMut;
+
+use crate::bounds::Bounds;
+
+/// A map overlay, such as a line, circle, or icon, drawn on top of the base
+/// tile layer once the map bounds have been resolved.
+pub trait Tool {
+    /// Draw this tool onto `pixmap`, using `bounds` to translate geographic
+    /// coordinates into pixel space.
+    fn draw(&self, bounds: &Bounds, pixmap: PixmapMut);
+}